@@ -4,6 +4,15 @@ pub enum Error {
     Parse(std::num::ParseIntError),
     Custom(String),
     Database(String),
+    /// An AEAD tag failed to verify, or a ciphertext was too short to contain
+    /// a nonce: the at-rest store is corrupt, truncated, or was opened with
+    /// the wrong passphrase. Also returned when the encrypted store is used
+    /// while no provider has been activated on the current thread.
+    DecryptionFailed,
+    /// The X.509 subject presented over a transport-layer (m)TLS connection
+    /// doesn't match the MLS credential identity the peer claims inside the
+    /// group.
+    IdentityMismatch(String),
 }
 
 // Create a type alias for Result
@@ -14,3 +23,15 @@ impl From<rusqlite::Error> for Error {
         Error::Database(err.to_string())
     }
 }
+
+impl From<sqlite_storage::error::Error> for Error {
+    fn from(err: sqlite_storage::error::Error) -> Self {
+        Error::Database(err.to_string())
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
@@ -0,0 +1 @@
+pub use crate::common::error::{Error, Result};
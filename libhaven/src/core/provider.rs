@@ -1,13 +1,26 @@
 use crate::common::prelude::*;
+use aes_gcm::aead::{Aead, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use bincode::Options;
 use openmls::prelude::{tls_codec::*, *};
 use openmls_basic_credential::SignatureKeyPair;
 use openmls_rust_crypto::{OpenMlsRustCrypto, RustCrypto};
 use openmls_sqlite_storage::{Codec, SqliteStorageProvider};
-use rusqlite::Connection;
+use rand::RngCore;
+use rusqlite::{Connection, OptionalExtension};
+use scrypt::Params as ScryptParams;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
 
 pub const CIPHERSUITE: Ciphersuite = Ciphersuite::MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519;
 
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
 #[derive(Default)]
 pub struct JsonCodec;
 
@@ -25,33 +38,245 @@ impl Codec for JsonCodec {
     }
 }
 
-pub struct SqliteOpenMlsProvider<'a> {
+/// A compact binary [`Codec`] for the production path, favoring size and
+/// commit latency over the debuggability of [`JsonCodec`]'s plaintext JSON.
+///
+/// `Codec::to_vec`/`from_slice` are bound to `serde::Serialize`/
+/// `DeserializeOwned`, not `tls_codec`'s own (de)serialization traits, so
+/// this can't call OpenMLS types' `tls_serialize_detached` directly even
+/// though they implement it. This is `bincode`'s fixed-width, big-endian,
+/// tag-free encoding driven through the same derived `serde` impls, not the
+/// TLS wire encoding: for the small, fixed-shape
+/// key-schedule and ratchet-tree records MLS writes on every epoch change,
+/// it's both smaller on disk and cheaper to encode/decode than JSON.
+#[derive(Default)]
+pub struct BincodeCodec;
+
+impl Codec for BincodeCodec {
+    type Error = bincode::Error;
+
+    fn to_vec<T: Serialize>(value: &T) -> std::result::Result<Vec<u8>, Self::Error> {
+        bincode::DefaultOptions::new()
+            .with_big_endian()
+            .with_fixint_encoding()
+            .serialize(value)
+    }
+
+    fn from_slice<T: serde::de::DeserializeOwned>(
+        slice: &[u8],
+    ) -> std::result::Result<T, Self::Error> {
+        bincode::DefaultOptions::new()
+            .with_big_endian()
+            .with_fixint_encoding()
+            .deserialize(slice)
+    }
+}
+
+thread_local! {
+    // `Codec::to_vec`/`from_slice` are associated functions with no `&self`,
+    // so `EncryptedCodec` has nowhere to carry the key for the store it's
+    // wrapping except thread-local state. Modeled as a stack rather than a
+    // single slot, pushed and popped by `ProviderGuard`, so that activating
+    // one provider while another is already active on the same thread
+    // nests correctly instead of silently stomping on it.
+    static ACTIVE_KEYS: RefCell<Vec<[u8; 32]>> = const { RefCell::new(Vec::new()) };
+}
+
+fn active_key() -> std::result::Result<[u8; 32], Error> {
+    ACTIVE_KEYS.with(|keys| keys.borrow().last().copied().ok_or(Error::DecryptionFailed))
+}
+
+/// RAII handle returned by [`SqliteOpenMlsProvider::activate`]. While held,
+/// [`EncryptedCodec`] encrypts and decrypts under this provider's master
+/// key; dropping the guard pops it back off, restoring whichever provider
+/// (if any) was active before.
+///
+/// A provider must be active for the duration of any `storage()` access it
+/// performs — unlike the old design, `storage()` no longer re-primes
+/// anything itself, so forgetting to activate fails loudly with
+/// `Error::DecryptionFailed` rather than silently reading or writing under
+/// the wrong key when two providers are alive on the same thread.
+#[must_use]
+pub struct ProviderGuard {
+    _private: (),
+}
+
+impl Drop for ProviderGuard {
+    fn drop(&mut self) {
+        ACTIVE_KEYS.with(|keys| {
+            keys.borrow_mut().pop();
+        });
+    }
+}
+
+/// Wraps an inner [`Codec`] with AES-256-GCM encryption-at-rest, so secrets
+/// OpenMLS asks the storage provider to persist (private keys, ratchet
+/// state, PSKs) never touch disk in plaintext.
+///
+/// `to_vec` serializes with `Inner`, then encrypts under the active
+/// provider's master key (see [`SqliteOpenMlsProvider::activate`]) with a
+/// nonce deterministically derived from the key and plaintext (see
+/// [`deterministic_nonce`]), emitting `nonce (12 bytes) || ciphertext ||
+/// tag`. `from_slice` reverses this.
+///
+/// The nonce has to be deterministic rather than random:
+/// `openmls_sqlite_storage` uses this same `Codec` to encode both entity
+/// values *and* the `Key`s it puts in `WHERE` clauses and `INSERT OR
+/// REPLACE` primary keys, so a key written once must re-encrypt to the
+/// exact same ciphertext on every later lookup, or reads never match and
+/// replaces turn into duplicate rows.
+#[derive(Default)]
+pub struct EncryptedCodec<Inner: Codec> {
+    _inner: std::marker::PhantomData<Inner>,
+}
+
+impl<Inner: Codec> Codec for EncryptedCodec<Inner> {
+    type Error = Error;
+
+    fn to_vec<T: Serialize>(value: &T) -> std::result::Result<Vec<u8>, Self::Error> {
+        let plaintext = Inner::to_vec(value)
+            .map_err(|_| Error::Custom("inner codec failed to serialize value".to_string()))?;
+        let key = active_key()?;
+        let nonce_bytes = deterministic_nonce(&key, &plaintext);
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce_bytes), plaintext.as_ref())
+            .map_err(|_| Error::DecryptionFailed)?;
+
+        let mut out = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        out.extend_from_slice(&nonce_bytes);
+        out.extend_from_slice(&ciphertext);
+        Ok(out)
+    }
+
+    fn from_slice<T: serde::de::DeserializeOwned>(
+        slice: &[u8],
+    ) -> std::result::Result<T, Self::Error> {
+        if slice.len() < NONCE_LEN {
+            return Err(Error::DecryptionFailed);
+        }
+        let (nonce_bytes, ciphertext) = slice.split_at(NONCE_LEN);
+        let key = active_key()?;
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(nonce_bytes), ciphertext)
+            .map_err(|_| Error::DecryptionFailed)?;
+
+        Inner::from_slice(&plaintext).map_err(|_| Error::DecryptionFailed)
+    }
+}
+
+/// Derives a 12-byte nonce as `SHA-256(key || plaintext)[..12]` instead of
+/// drawing a fresh random one per call, making encryption a deterministic
+/// function of `(key, plaintext)`: equal plaintexts under the same key
+/// always produce equal ciphertexts, which is what lets `EncryptedCodec`
+/// double as the codec for both entity values and lookup keys. The
+/// trade-off is the usual one for deterministic AEAD — two equal
+/// plaintexts become distinguishable from two different ones — which is
+/// unavoidable for data that the storage layer also matches on.
+fn deterministic_nonce(key: &[u8; 32], plaintext: &[u8]) -> [u8; NONCE_LEN] {
+    let mut hasher = Sha256::new();
+    hasher.update(key);
+    hasher.update(plaintext);
+    let digest = hasher.finalize();
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce.copy_from_slice(&digest[..NONCE_LEN]);
+    nonce
+}
+
+/// Derives the store's master key from `passphrase` via scrypt, storing the
+/// salt and scrypt parameters in a one-row metadata table on first open so
+/// the same key can be re-derived the next time the store is opened.
+fn derive_master_key(connection: &Connection, passphrase: &str) -> Result<[u8; 32]> {
+    connection.execute_batch(
+        "CREATE TABLE IF NOT EXISTS encryption_metadata (
+            id INTEGER PRIMARY KEY CHECK (id = 0),
+            salt BLOB NOT NULL,
+            log_n INTEGER NOT NULL,
+            r INTEGER NOT NULL,
+            p INTEGER NOT NULL
+        );",
+    )?;
+
+    let existing: Option<(Vec<u8>, u8, u32, u32)> = connection
+        .query_row(
+            "SELECT salt, log_n, r, p FROM encryption_metadata WHERE id = 0",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .optional()?;
+
+    let (salt, log_n, r, p) = match existing {
+        Some(row) => row,
+        None => {
+            let mut salt = [0u8; SALT_LEN];
+            OsRng.fill_bytes(&mut salt);
+            connection.execute(
+                "INSERT INTO encryption_metadata (id, salt, log_n, r, p) VALUES (0, ?1, ?2, ?3, ?4)",
+                rusqlite::params![salt.to_vec(), SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P],
+            )?;
+            (salt.to_vec(), SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P)
+        }
+    };
+
+    let scrypt_params = ScryptParams::new(log_n, r, p, 32)
+        .map_err(|e| Error::Custom(format!("invalid scrypt parameters: {e}")))?;
+    let mut key = [0u8; 32];
+    scrypt::scrypt(passphrase.as_bytes(), &salt, &scrypt_params, &mut key)
+        .map_err(|e| Error::Custom(format!("scrypt key derivation failed: {e}")))?;
+    Ok(key)
+}
+
+/// Generic over the inner [`Codec`]: pick [`JsonCodec`] for a debuggable,
+/// human-readable store, or [`BincodeCodec`] for the compact encoding production
+/// deployments want. Either way the records are further wrapped by
+/// [`EncryptedCodec`] before they touch disk.
+pub struct SqliteOpenMlsProvider<'a, C: Codec = JsonCodec> {
     pub crypto: RustCrypto,
-    pub key_store: SqliteStorageProvider<JsonCodec, &'a Connection>,
+    pub key_store: SqliteStorageProvider<EncryptedCodec<C>, &'a Connection>,
     pub db_connection: rusqlite::Connection,
+    master_key: [u8; 32],
 }
 
-impl SqliteOpenMlsProvider {
-    pub fn new(db_path: &str) -> Result<Self> {
+impl<C: Codec + Default> SqliteOpenMlsProvider<'_, C> {
+    /// Opens (or creates) an encrypted-at-rest SQLite store, encoding
+    /// records with `C` before encryption. `passphrase` is run through
+    /// scrypt to derive the AES-256-GCM master key that protects every
+    /// secret OpenMLS writes to `db_path`.
+    pub fn new(db_path: &str, passphrase: &str) -> Result<Self> {
         let connection = rusqlite::Connection::open(db_path)?;
-        let mut storage =
-            openmls_sqlite_storage::SqliteStorageProvider::<JsonCodec, &mut Connection>::new(
-                &mut connection,
-            );
+        let master_key = derive_master_key(&connection, passphrase)?;
+        let mut storage = openmls_sqlite_storage::SqliteStorageProvider::<
+            EncryptedCodec<C>,
+            &mut Connection,
+        >::new(&mut connection);
         storage.run_migrations().expect("Failed to run migrations.");
         let provider = SqliteOpenMlsProvider {
             crypto: RustCrypto::default(),
             key_store: storage,
             db_connection: connection,
+            master_key,
         };
         Ok(provider)
     }
+
+    /// Makes this provider's master key the one [`EncryptedCodec`] uses on
+    /// this thread for as long as the returned [`ProviderGuard`] is held.
+    /// Call this before any OpenMLS operation that touches `storage()` —
+    /// required as soon as more than one provider might be alive on the
+    /// same thread (e.g. two participants sharing one process), since the
+    /// thread-local key `EncryptedCodec` reads has no other way to know
+    /// which provider is "current".
+    pub fn activate(&self) -> ProviderGuard {
+        ACTIVE_KEYS.with(|keys| keys.borrow_mut().push(self.master_key));
+        ProviderGuard { _private: () }
+    }
 }
 
-impl OpenMlsProvider for SqliteOpenMlsProvider {
+impl<C: Codec> OpenMlsProvider for SqliteOpenMlsProvider<'_, C> {
     type CryptoProvider = RustCrypto;
     type RandProvider = RustCrypto;
-    type StorageProvider = SqliteStorageProvider<JsonCodec, Connection>;
+    type StorageProvider = SqliteStorageProvider<EncryptedCodec<C>, Connection>;
 
     fn storage(&self) -> &Self::StorageProvider {
         &self.key_store
@@ -65,3 +290,95 @@ impl OpenMlsProvider for SqliteOpenMlsProvider {
         &self.crypto
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn credential_and_key_package<C: Codec>(
+        identity: &str,
+        provider: &SqliteOpenMlsProvider<C>,
+    ) -> (CredentialWithKey, SignatureKeyPair, KeyPackageBundle) {
+        let _guard = provider.activate();
+        let credential = BasicCredential::new(identity.as_bytes().to_vec());
+        let signer = SignatureKeyPair::new(CIPHERSUITE.signature_algorithm()).unwrap();
+        signer.store(provider.storage()).unwrap();
+        let credential_with_key = CredentialWithKey {
+            credential: credential.into(),
+            signature_key: signer.public().into(),
+        };
+        let key_package = KeyPackage::builder()
+            .build(CIPHERSUITE, provider, &signer, credential_with_key.clone())
+            .unwrap();
+        (credential_with_key, signer, key_package)
+    }
+
+    /// Builds a 1-plus-3-member group under `C` and returns the on-disk
+    /// store size and wall-clock time to create and merge the add commit.
+    fn bench_multi_member_add<C: Codec + Default>(db_path: &str) -> (u64, Duration) {
+        let _ = std::fs::remove_file(db_path);
+        let creator = SqliteOpenMlsProvider::<C>::new(db_path, "benchmark passphrase").unwrap();
+        let (creator_credential, creator_signer, _) = credential_and_key_package("Creator", &creator);
+
+        let mut group = {
+            let _guard = creator.activate();
+            MlsGroup::new(
+                &creator,
+                &creator_signer,
+                &MlsGroupCreateConfig::default(),
+                creator_credential,
+            )
+            .unwrap()
+        };
+
+        let member_names = ["Member1", "Member2", "Member3"];
+        let key_packages: Vec<KeyPackage> = member_names
+            .iter()
+            .map(|name| {
+                let member_db = format!("{db_path}.{name}");
+                let _ = std::fs::remove_file(&member_db);
+                let provider =
+                    SqliteOpenMlsProvider::<C>::new(&member_db, "benchmark passphrase").unwrap();
+                let (_, _, bundle) = credential_and_key_package(name, &provider);
+                bundle.key_package().clone()
+            })
+            .collect();
+
+        let start = Instant::now();
+        {
+            let _guard = creator.activate();
+            group
+                .add_members(&creator, &creator_signer, &key_packages)
+                .unwrap();
+            group.merge_pending_commit(&creator).unwrap();
+        }
+        let elapsed = start.elapsed();
+
+        drop(creator);
+        let size = std::fs::metadata(db_path).unwrap().len();
+
+        let _ = std::fs::remove_file(db_path);
+        for name in member_names {
+            let _ = std::fs::remove_file(format!("{db_path}.{name}"));
+        }
+
+        (size, elapsed)
+    }
+
+    #[test]
+    fn bincode_codec_takes_no_more_space_than_json_for_a_multi_member_add() {
+        let (json_size, json_time) = bench_multi_member_add::<JsonCodec>("bench_json_codec.db");
+        let (bincode_size, bincode_time) =
+            bench_multi_member_add::<BincodeCodec>("bench_bincode_codec.db");
+
+        println!("JsonCodec: {json_size} bytes, {json_time:?} to commit a 4-member add");
+        println!("BincodeCodec: {bincode_size} bytes, {bincode_time:?} to commit a 4-member add");
+
+        assert!(
+            bincode_size <= json_size,
+            "expected BincodeCodec's compact encoding to take no more space on disk than \
+             JsonCodec's JSON ({bincode_size} vs {json_size} bytes)"
+        );
+    }
+}
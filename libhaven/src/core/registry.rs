@@ -0,0 +1,121 @@
+use crate::common::prelude::*;
+use crate::core::provider::SqliteOpenMlsProvider;
+use openmls::group::{GroupId, MlsGroup};
+use openmls_sqlite_storage::Codec;
+use sqlite_storage::DbConnection;
+
+/// What the registry knows about one joined group, without having to
+/// rehydrate the full `MlsGroup` from storage.
+#[derive(Debug, Clone)]
+pub struct GroupSummary {
+    pub group_id: Vec<u8>,
+    pub local_identity: Vec<u8>,
+    pub display_name: String,
+    pub epoch: u64,
+}
+
+/// Tracks which groups the local identity belongs to, so a freshly launched
+/// client can list and rejoin its existing conversations instead of only
+/// ever knowing about groups created earlier in the same process.
+pub struct GroupRegistry<'conn> {
+    conn: DbConnection<'conn>,
+}
+
+impl<'conn> GroupRegistry<'conn> {
+    pub fn new(mut conn: DbConnection<'conn>) -> Result<Self> {
+        Self::run_migrations(&mut conn)?;
+        Ok(Self { conn })
+    }
+
+    fn run_migrations(conn: &mut DbConnection) -> Result<()> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS group_registry (
+                group_id BLOB PRIMARY KEY,
+                local_identity BLOB NOT NULL,
+                display_name TEXT NOT NULL,
+                epoch INTEGER NOT NULL
+            );",
+        )?;
+        Ok(())
+    }
+
+    /// Records that `local_identity` has joined `group_id`, or updates its
+    /// epoch/display name if it's already registered.
+    pub fn record_group(
+        &mut self,
+        group_id: &[u8],
+        local_identity: &[u8],
+        display_name: &str,
+        epoch: u64,
+    ) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO group_registry (group_id, local_identity, display_name, epoch)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(group_id) DO UPDATE SET display_name = excluded.display_name, epoch = excluded.epoch",
+            rusqlite::params![group_id, local_identity, display_name, epoch as i64],
+        )?;
+        Ok(())
+    }
+
+    /// Lists every group the registry knows about, for rendering a
+    /// conversation list on startup.
+    pub fn list_groups(&mut self) -> Result<Vec<GroupSummary>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT group_id, local_identity, display_name, epoch FROM group_registry ORDER BY group_id")?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(GroupSummary {
+                    group_id: row.get(0)?,
+                    local_identity: row.get(1)?,
+                    display_name: row.get(2)?,
+                    epoch: row.get::<_, i64>(3)? as u64,
+                })
+            })
+            .map_err(|e| sqlite_storage::error::Error::DatabaseError(e.to_string()))?;
+
+        let mut groups = Vec::new();
+        for row in rows {
+            groups.push(row.map_err(|e| sqlite_storage::error::Error::DatabaseError(e.to_string()))?);
+        }
+        Ok(groups)
+    }
+
+    /// Rehydrates a previously joined group from the storage provider,
+    /// letting a client rejoin a conversation without re-running the
+    /// welcome handshake. Returns `None` if the group is not registered, or
+    /// if the provider has no persisted state for it.
+    pub fn load_group<C: Codec>(
+        &mut self,
+        provider: &SqliteOpenMlsProvider<C>,
+        group_id: &[u8],
+    ) -> Result<Option<MlsGroup>> {
+        if !self.is_registered(group_id)? {
+            return Ok(None);
+        }
+        let id = GroupId::from_slice(group_id);
+        let _guard = provider.activate();
+        MlsGroup::load(provider.storage(), &id)
+            .map_err(|e| Error::Custom(format!("failed to load group {id:?}: {e:?}")))
+    }
+
+    /// Stops tracking `group_id`, e.g. after the user leaves or deletes the
+    /// conversation. Does not touch the underlying MLS group state.
+    pub fn forget_group(&mut self, group_id: &[u8]) -> Result<()> {
+        self.conn.execute(
+            "DELETE FROM group_registry WHERE group_id = ?1",
+            rusqlite::params![group_id],
+        )?;
+        Ok(())
+    }
+
+    fn is_registered(&mut self, group_id: &[u8]) -> Result<bool> {
+        self.conn
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM group_registry WHERE group_id = ?1)",
+                rusqlite::params![group_id],
+                |row| row.get(0),
+            )
+            .map_err(Into::into)
+    }
+}
@@ -5,15 +5,38 @@ use openmls_rust_crypto::{OpenMlsRustCrypto, RustCrypto};
 use openmls_sqlite_storage::{Codec, SqliteStorageProvider};
 use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
+use sqlite_storage::history::HistoryStore;
+use sqlite_storage::DbConnection;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 mod common;
 mod core;
+mod transport;
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock is before the Unix epoch")
+        .as_millis() as i64
+}
 
 fn main() {
     // Define ciphersuite ...
     // ... and the crypto provider to use.
-    let sasha_connection = SqliteOpenMlsProvider::new("sasha.db").unwrap();
-    let maxim_connection = SqliteOpenMlsProvider::new("maxim.db").unwrap();
+    let sasha_connection = SqliteOpenMlsProvider::new("sasha.db", "sasha's passphrase").unwrap();
+    let maxim_connection = SqliteOpenMlsProvider::new("maxim.db", "maxim's passphrase").unwrap();
+
+    // Separate connections to the same files for the history subsystem,
+    // which is layered on `DbConnection` rather than the OpenMLS storage
+    // provider.
+    let mut sasha_history = DbConnection::new(
+        Connection::open("sasha.db").expect("Error opening Sasha's history store."),
+    );
+    HistoryStore::run_migrations(&mut sasha_history).expect("Error running history migrations.");
+    let mut maxim_history = DbConnection::new(
+        Connection::open("maxim.db").expect("Error opening Maxim's history store."),
+    );
+    HistoryStore::run_migrations(&mut maxim_history).expect("Error running history migrations.");
 
     // Now let's create two participants.
 
@@ -58,42 +81,54 @@ fn main() {
     }
 
     // First they need credentials to identify them
-    let (sasha_credential_with_key, sasha_signer) = generate_credential_with_key(
-        "Sasha".into(),
-        CredentialType::Basic,
-        crate::core::provider::CIPHERSUITE.signature_algorithm(),
-        &sasha_connection,
-    );
+    let (sasha_credential_with_key, sasha_signer) = {
+        let _guard = sasha_connection.activate();
+        generate_credential_with_key(
+            "Sasha".into(),
+            CredentialType::Basic,
+            crate::core::provider::CIPHERSUITE.signature_algorithm(),
+            &sasha_connection,
+        )
+    };
 
-    let (maxim_credential_with_key, maxim_signer) = generate_credential_with_key(
-        "Maxim".into(),
-        CredentialType::Basic,
-        crate::core::provider::CIPHERSUITE.signature_algorithm(),
-        &maxim_connection,
-    );
+    let (maxim_credential_with_key, maxim_signer) = {
+        let _guard = maxim_connection.activate();
+        generate_credential_with_key(
+            "Maxim".into(),
+            CredentialType::Basic,
+            crate::core::provider::CIPHERSUITE.signature_algorithm(),
+            &maxim_connection,
+        )
+    };
 
     // Then they generate key packages to facilitate the asynchronous handshakes
     // in MLS
 
     // Generate KeyPackages
-    let maxim_key_package = generate_key_package(
-        crate::core::provider::CIPHERSUITE,
-        &maxim_connection,
-        &maxim_signer,
-        maxim_credential_with_key,
-    );
+    let maxim_key_package = {
+        let _guard = maxim_connection.activate();
+        generate_key_package(
+            crate::core::provider::CIPHERSUITE,
+            &maxim_connection,
+            &maxim_signer,
+            maxim_credential_with_key,
+        )
+    };
 
     // Now in practice, Maxim would need to upload this keypackage somewhere.
     // And Sasha would have to retrieve it.
 
     // Now Sasha starts a new group ...
-    let mut sasha_group = MlsGroup::new(
-        &sasha_connection,
-        &sasha_signer,
-        &MlsGroupCreateConfig::default(),
-        sasha_credential_with_key,
-    )
-    .expect("An unexpected error occurred.");
+    let mut sasha_group = {
+        let _guard = sasha_connection.activate();
+        MlsGroup::new(
+            &sasha_connection,
+            &sasha_signer,
+            &MlsGroupCreateConfig::default(),
+            sasha_credential_with_key,
+        )
+        .expect("An unexpected error occurred.")
+    };
 
     // ... and invites Maxim.
     // The key package has to be retrieved from Maxim in some way. Most likely
@@ -101,18 +136,30 @@ fn main() {
     // mls_message_out is the commit message that would need to be sent to all
     // existing group members if this wasn't a 1-on-1 chat.
     // welcome_out and group_info should be sent to the individual that is being added.
-    let (mls_message_out, welcome_out, group_info) = sasha_group
-        .add_members(
-            &sasha_connection,
-            &sasha_signer,
-            std::slice::from_ref(maxim_key_package.key_package()),
-        )
-        .expect("Could not add members.");
+    let (mls_message_out, welcome_out, group_info) = {
+        let _guard = sasha_connection.activate();
+        sasha_group
+            .add_members(
+                &sasha_connection,
+                &sasha_signer,
+                std::slice::from_ref(maxim_key_package.key_package()),
+            )
+            .expect("Could not add members.")
+    };
 
     // Sasha merges the pending commit that adds Maxim.
-    sasha_group
-        .merge_pending_commit(&sasha_connection)
-        .expect("error merging pending commit");
+    {
+        let _guard = sasha_connection.activate();
+        sasha_group
+            .merge_pending_commit(&sasha_connection)
+            .expect("error merging pending commit");
+    }
+
+    // Register the group with history now, rather than waiting for the
+    // first message, so `history_latest` reports `Empty` instead of
+    // `TargetNotFound` even before anyone has said anything.
+    HistoryStore::register_group(&mut sasha_history, sasha_group.group_id().as_slice())
+        .expect("Error registering group with Sasha's history store.");
 
     // Sasha serializes the [`MlsMessageOut`] containing the [`Welcome`].
     let serialized_welcome = tls_codec::Serialize::tls_serialize_detached(&welcome_out)
@@ -134,26 +181,53 @@ fn main() {
     };
 
     // Now Maxim can build a staged join for the group in order to inspect the welcome
-    let maxim_staged_join = StagedWelcome::new_from_welcome(
-        &maxim_connection,
-        &MlsGroupJoinConfig::default(),
-        welcome,
-        // The public tree is needed and transferred out of band.
-        // It is also possible to use the [`RatchetTreeExtension`]
-        Some(sasha_group.export_ratchet_tree().into()),
-    )
-    .expect("Error creating a staged join from Welcome");
+    let maxim_staged_join = {
+        let _guard = maxim_connection.activate();
+        StagedWelcome::new_from_welcome(
+            &maxim_connection,
+            &MlsGroupJoinConfig::default(),
+            welcome,
+            // The public tree is needed and transferred out of band.
+            // It is also possible to use the [`RatchetTreeExtension`]
+            Some(sasha_group.export_ratchet_tree().into()),
+        )
+        .expect("Error creating a staged join from Welcome")
+    };
 
     // Finally, Maxim can create the group
-    let mut maxim_group = maxim_staged_join
-        .into_group(&maxim_connection)
-        .expect("Error creating the group from the staged join");
+    let mut maxim_group = {
+        let _guard = maxim_connection.activate();
+        maxim_staged_join
+            .into_group(&maxim_connection)
+            .expect("Error creating the group from the staged join")
+    };
+
+    HistoryStore::register_group(&mut maxim_history, maxim_group.group_id().as_slice())
+        .expect("Error registering group with Maxim's history store.");
 
     // Now sasha can send Maxim a message!
     let message_alice = b"Hi, I'm Alice!";
-    let mls_message_out = sasha_group
-        .create_message(&sasha_connection, &sasha_signer, message_alice)
-        .expect("Error creating application message.");
+    let mls_message_out = {
+        let _guard = sasha_connection.activate();
+        sasha_group
+            .create_message(&sasha_connection, &sasha_signer, message_alice)
+            .expect("Error creating application message.")
+    };
+
+    // Record the outbound message in Sasha's own history before handing it
+    // off, so a restart mid-send still leaves a trail.
+    HistoryStore::record_message(
+        &mut sasha_history,
+        sasha_group.group_id().as_slice(),
+        b"Sasha",
+        sasha_group.epoch().as_u64(),
+        true,
+        message_alice,
+        now_ms(),
+        true,
+    )
+    .expect("Error recording outbound message in Sasha's history store.");
+
     // Serialize the message.
     let serialized_message = tls_codec::Serialize::tls_serialize_detached(&mls_message_out)
         .expect("Error serializing message to Maxim.");
@@ -166,15 +240,33 @@ fn main() {
         .try_into_protocol_message()
         .expect("Could not convert message to protocol message.");
     println!("{:?}", protocol_message);
-    let processed_message = maxim_group
-        .process_message(&maxim_connection, protocol_message)
-        .expect("Could not process message.");
+    let processed_message = {
+        let _guard = maxim_connection.activate();
+        maxim_group
+            .process_message(&maxim_connection, protocol_message)
+            .expect("Could not process message.")
+    };
     println!("processed message: {:?}", processed_message);
     let message_content = processed_message.into_content();
     println!("message content: {:?}", message_content);
     if let ProcessedMessageContent::ApplicationMessage(application_message) = message_content {
+        let body = application_message.into_bytes();
+
+        // Record the inbound message in Maxim's history before acting on it.
+        HistoryStore::record_message(
+            &mut maxim_history,
+            maxim_group.group_id().as_slice(),
+            b"Sasha",
+            maxim_group.epoch().as_u64(),
+            false,
+            &body,
+            now_ms(),
+            true,
+        )
+        .expect("Error recording inbound message in Maxim's history store.");
+
         // Check the message
-        assert_eq!(application_message.into_bytes(), b"Hi, I'm Alice!");
+        assert_eq!(body, b"Hi, I'm Alice!");
     }
 
     println!("Done!")
@@ -0,0 +1,44 @@
+use crate::common::prelude::*;
+use crate::transport::framing::{read_framed, write_framed};
+use openmls::prelude::{MlsMessageIn, MlsMessageOut};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::{ClientConfig, RootCertStore};
+use std::net::TcpStream;
+use std::sync::Arc;
+
+/// The client side of the mTLS transport: connects, presents a client
+/// certificate, and exchanges framed MLS messages with a [`TransportServer`](crate::transport::TransportServer).
+pub struct TransportClient {
+    inner: rustls::StreamOwned<rustls::ClientConnection, TcpStream>,
+}
+
+impl TransportClient {
+    pub fn connect(
+        addr: &str,
+        server_name: ServerName<'static>,
+        root_store: RootCertStore,
+        client_cert_chain: Vec<CertificateDer<'static>>,
+        client_key: PrivateKeyDer<'static>,
+    ) -> Result<Self> {
+        let config = ClientConfig::builder()
+            .with_root_certificates(root_store)
+            .with_client_auth_cert(client_cert_chain, client_key)
+            .map_err(|e| Error::Custom(format!("invalid client certificate: {e}")))?;
+
+        let conn = rustls::ClientConnection::new(Arc::new(config), server_name)
+            .map_err(|e| Error::Custom(format!("failed to start TLS session: {e}")))?;
+        let tcp = TcpStream::connect(addr)?;
+
+        Ok(Self {
+            inner: rustls::StreamOwned::new(conn, tcp),
+        })
+    }
+
+    pub fn send(&mut self, message: &MlsMessageOut) -> Result<()> {
+        write_framed(&mut self.inner, message)
+    }
+
+    pub fn recv(&mut self) -> Result<MlsMessageIn> {
+        read_framed(&mut self.inner)
+    }
+}
@@ -0,0 +1,27 @@
+use crate::common::prelude::*;
+use openmls::prelude::tls_codec::{Deserialize as TlsDeserialize, Serialize as TlsSerialize};
+use openmls::prelude::{MlsMessageIn, MlsMessageOut};
+use std::io::{Read, Write};
+
+/// Writes `message` as a 4-byte big-endian length prefix followed by its TLS
+/// wire encoding.
+pub fn write_framed(writer: &mut impl Write, message: &MlsMessageOut) -> Result<()> {
+    let bytes = message
+        .tls_serialize_detached()
+        .map_err(|e| Error::Custom(format!("failed to serialize message: {e}")))?;
+    let len = u32::try_from(bytes.len())
+        .map_err(|_| Error::Custom("message too large to frame".to_string()))?;
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(&bytes)?;
+    Ok(())
+}
+
+/// Reads one length-prefixed message written by `write_framed`.
+pub fn read_framed(reader: &mut impl Read) -> Result<MlsMessageIn> {
+    let mut len_bytes = [0u8; 4];
+    reader.read_exact(&mut len_bytes)?;
+    let mut buf = vec![0u8; u32::from_be_bytes(len_bytes) as usize];
+    reader.read_exact(&mut buf)?;
+    MlsMessageIn::tls_deserialize_exact(buf.as_slice())
+        .map_err(|e| Error::Custom(format!("failed to deserialize message: {e}")))
+}
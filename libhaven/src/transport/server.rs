@@ -0,0 +1,123 @@
+use crate::common::prelude::*;
+use crate::transport::framing::{read_framed, write_framed};
+use openmls::prelude::{Credential, MlsMessageIn, MlsMessageOut};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use std::io::Write;
+use std::net::TcpListener;
+use std::sync::Arc;
+
+/// A TLS connection that has completed a handshake requiring the peer to
+/// present a client certificate, carrying framed MLS messages.
+pub struct AuthenticatedStream {
+    inner: rustls::StreamOwned<rustls::ServerConnection, std::net::TcpStream>,
+}
+
+impl AuthenticatedStream {
+    pub fn send(&mut self, message: &MlsMessageOut) -> Result<()> {
+        write_framed(&mut self.inner, message)
+    }
+
+    pub fn recv(&mut self) -> Result<MlsMessageIn> {
+        read_framed(&mut self.inner)
+    }
+}
+
+/// A TLS server that requires a client certificate (mutual TLS) on every
+/// connection, so the application can bind the transport-layer identity to
+/// an MLS `Credential` before trusting anything it sends.
+pub struct TransportServer {
+    listener: TcpListener,
+    config: Arc<ServerConfig>,
+}
+
+impl TransportServer {
+    pub fn bind(
+        addr: &str,
+        server_cert_chain: Vec<CertificateDer<'static>>,
+        server_key: PrivateKeyDer<'static>,
+        client_root_store: RootCertStore,
+    ) -> Result<Self> {
+        let client_verifier = WebPkiClientVerifier::builder(Arc::new(client_root_store))
+            .build()
+            .map_err(|e| Error::Custom(format!("failed to build client verifier: {e}")))?;
+
+        let config = ServerConfig::builder()
+            .with_client_cert_verifier(client_verifier)
+            .with_single_cert(server_cert_chain, server_key)
+            .map_err(|e| Error::Custom(format!("invalid server certificate: {e}")))?;
+
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            config: Arc::new(config),
+        })
+    }
+
+    /// Accepts one connection, completes the mTLS handshake, and returns the
+    /// authenticated stream along with the client's DER-encoded leaf
+    /// certificate so the caller can bind it to an MLS credential via
+    /// [`verify_identity`].
+    pub fn accept(&self) -> Result<(AuthenticatedStream, CertificateDer<'static>)> {
+        let (tcp, _addr) = self.listener.accept()?;
+        let conn = rustls::ServerConnection::new(self.config.clone())
+            .map_err(|e| Error::Custom(format!("failed to start TLS session: {e}")))?;
+        let mut stream = rustls::StreamOwned::new(conn, tcp);
+        // Flushing with nothing queued still drives the handshake to
+        // completion, which is what makes the peer certificate available.
+        stream.flush()?;
+
+        let leaf = stream
+            .conn
+            .peer_certificates()
+            .and_then(|certs| certs.first())
+            .cloned()
+            .ok_or_else(|| Error::Custom("client did not present a certificate".to_string()))?;
+
+        Ok((AuthenticatedStream { inner: stream }, leaf))
+    }
+}
+
+/// Parses the subject of a DER-encoded X.509 certificate.
+pub fn subject_identity(cert: &CertificateDer) -> Result<String> {
+    let (_, parsed) = x509_parser::parse_x509_certificate(cert.as_ref())
+        .map_err(|e| Error::Custom(format!("failed to parse client certificate: {e}")))?;
+    Ok(parsed.subject().to_string())
+}
+
+/// Extracts the certificate subject's Common Name (CN) attribute, which is
+/// what a transport-layer identity should be checked against rather than the
+/// subject's full distinguished name.
+fn subject_common_name(cert: &CertificateDer) -> Result<String> {
+    let (_, parsed) = x509_parser::parse_x509_certificate(cert.as_ref())
+        .map_err(|e| Error::Custom(format!("failed to parse client certificate: {e}")))?;
+    let common_name = parsed
+        .subject()
+        .iter_common_name()
+        .next()
+        .ok_or_else(|| Error::Custom("client certificate has no subject Common Name".to_string()))?;
+    common_name
+        .as_str()
+        .map(str::to_string)
+        .map_err(|e| Error::Custom(format!("subject Common Name is not valid UTF-8: {e}")))
+}
+
+/// Rejects a connection whose certificate subject Common Name doesn't
+/// exactly match the MLS credential's identity, so a transport-layer peer
+/// can't present one identity to TLS and a different one inside the group.
+///
+/// This compares for equality, not substring containment: `"Alice"` does
+/// not match a certificate whose Common Name is `"Alice2"` or `"NotAlice"`.
+/// Subject attributes other than the Common Name (such as `O`) are not
+/// compared.
+pub fn verify_identity(cert: &CertificateDer, credential: &Credential) -> Result<()> {
+    let common_name = subject_common_name(cert)?;
+    let expected = String::from_utf8_lossy(credential.identity());
+    if common_name == expected {
+        Ok(())
+    } else {
+        Err(Error::IdentityMismatch(format!(
+            "certificate Common Name '{common_name}' does not match MLS credential identity '{expected}'"
+        )))
+    }
+}
@@ -0,0 +1,147 @@
+use crate::db_connection::DbConnection;
+use crate::error::Error;
+
+/// A message waiting to be fetched by its recipient.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueuedMessage {
+    pub id: i64,
+    pub recipient: Vec<u8>,
+    pub payload: Vec<u8>,
+}
+
+/// Publishes key packages and relays `MlsMessageOut` blobs between clients
+/// that aren't online at the same time, replacing the "somehow send this"
+/// steps a two-party demo can get away with skipping.
+pub trait DeliveryService {
+    /// Publishes a key package on behalf of `identity` for others to claim.
+    fn publish_key_package(&mut self, identity: &[u8], key_package: Vec<u8>) -> Result<(), Error>;
+
+    /// Claims and removes one of `identity`'s published key packages, so no
+    /// two inviters can race each other onto the same one.
+    fn claim_key_package(&mut self, identity: &[u8]) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Enqueues a framed message for `recipient` to pick up later.
+    fn enqueue(&mut self, recipient: &[u8], message: Vec<u8>) -> Result<(), Error>;
+
+    /// Returns up to `limit` unacknowledged messages for `recipient`, oldest
+    /// first.
+    fn fetch(&mut self, recipient: &[u8], limit: usize) -> Result<Vec<QueuedMessage>, Error>;
+
+    /// Marks every message for `recipient` up to and including `up_to_id` as
+    /// delivered, so it isn't returned by `fetch` again.
+    fn acknowledge(&mut self, recipient: &[u8], up_to_id: i64) -> Result<(), Error>;
+}
+
+/// A [`DeliveryService`] backed by `DbConnection`.
+pub struct SqliteDeliveryService<'conn> {
+    conn: DbConnection<'conn>,
+}
+
+impl<'conn> SqliteDeliveryService<'conn> {
+    pub fn new(mut conn: DbConnection<'conn>) -> Result<Self, Error> {
+        Self::run_migrations(&mut conn)?;
+        Ok(Self { conn })
+    }
+
+    fn run_migrations(conn: &mut DbConnection) -> Result<(), Error> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS delivery_key_packages (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                identity BLOB NOT NULL,
+                key_package BLOB NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS delivery_queue (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                recipient BLOB NOT NULL,
+                payload BLOB NOT NULL,
+                acknowledged INTEGER NOT NULL DEFAULT 0
+            );",
+        )
+    }
+}
+
+impl<'conn> DeliveryService for SqliteDeliveryService<'conn> {
+    fn publish_key_package(&mut self, identity: &[u8], key_package: Vec<u8>) -> Result<(), Error> {
+        self.conn.execute(
+            "INSERT INTO delivery_key_packages (identity, key_package) VALUES (?1, ?2)",
+            rusqlite::params![identity, key_package],
+        )?;
+        Ok(())
+    }
+
+    fn claim_key_package(&mut self, identity: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let mut txn = self.conn.new_transaction()?;
+
+        let candidate = {
+            let mut stmt = txn.prepare(
+                "SELECT id, key_package FROM delivery_key_packages
+                 WHERE identity = ?1 ORDER BY id ASC LIMIT 1",
+            )?;
+            let mut rows = stmt
+                .query_map(rusqlite::params![identity], |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get::<_, Vec<u8>>(1)?))
+                })
+                .map_err(|e| Error::DatabaseError(e.to_string()))?;
+            match rows.next() {
+                Some(row) => Some(row.map_err(|e| Error::DatabaseError(e.to_string()))?),
+                None => None,
+            }
+        };
+
+        let Some((id, key_package)) = candidate else {
+            txn.commit()?;
+            return Ok(None);
+        };
+
+        let deleted = txn.execute(
+            "DELETE FROM delivery_key_packages WHERE id = ?1",
+            rusqlite::params![id],
+        )?;
+        txn.commit()?;
+
+        // Another connection claimed (and deleted) this same row between
+        // our SELECT and our DELETE; we lost the race, not them.
+        if deleted == 0 {
+            return Ok(None);
+        }
+        Ok(Some(key_package))
+    }
+
+    fn enqueue(&mut self, recipient: &[u8], message: Vec<u8>) -> Result<(), Error> {
+        self.conn.execute(
+            "INSERT INTO delivery_queue (recipient, payload) VALUES (?1, ?2)",
+            rusqlite::params![recipient, message],
+        )?;
+        Ok(())
+    }
+
+    fn fetch(&mut self, recipient: &[u8], limit: usize) -> Result<Vec<QueuedMessage>, Error> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, recipient, payload FROM delivery_queue
+             WHERE recipient = ?1 AND acknowledged = 0 ORDER BY id ASC LIMIT ?2",
+        )?;
+        let rows = stmt
+            .query_map(rusqlite::params![recipient, limit as i64], |row| {
+                Ok(QueuedMessage {
+                    id: row.get(0)?,
+                    recipient: row.get(1)?,
+                    payload: row.get(2)?,
+                })
+            })
+            .map_err(|e| Error::DatabaseError(e.to_string()))?;
+
+        let mut messages = Vec::new();
+        for row in rows {
+            messages.push(row.map_err(|e| Error::DatabaseError(e.to_string()))?);
+        }
+        Ok(messages)
+    }
+
+    fn acknowledge(&mut self, recipient: &[u8], up_to_id: i64) -> Result<(), Error> {
+        self.conn.execute(
+            "UPDATE delivery_queue SET acknowledged = 1 WHERE recipient = ?1 AND id <= ?2",
+            rusqlite::params![recipient, up_to_id],
+        )?;
+        Ok(())
+    }
+}
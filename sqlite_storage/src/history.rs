@@ -0,0 +1,282 @@
+use crate::db_connection::DbConnection;
+use crate::error::Error;
+
+/// A single message recovered from history, in delivery order within its group.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredMessage {
+    pub group_id: Vec<u8>,
+    pub sender_identity: Vec<u8>,
+    /// Monotonically increasing per-group sequence number, starting at 0.
+    pub sequence: i64,
+    /// Wall-clock time the message was recorded, in epoch milliseconds.
+    pub timestamp_ms: i64,
+    /// The MLS group epoch in effect when the message was sent.
+    pub epoch: u64,
+    /// `true` if this client sent the message, `false` if it was received.
+    pub is_outbound: bool,
+    pub body: Vec<u8>,
+}
+
+/// Result of a CHATHISTORY-style query. Distinguishes "the group has no
+/// messages in range" from "we have never heard of this group", which a bare
+/// `Vec` can't express.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HistoryResult {
+    Messages(Vec<StoredMessage>),
+    Empty,
+    TargetNotFound,
+}
+
+/// An anchor for a CHATHISTORY-style range query: either side of a
+/// `history_before`/`history_after`/`history_between` call can be pinned to a
+/// sequence number or to a timestamp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    Sequence(i64),
+    TimestampMs(i64),
+}
+
+/// Persists inbound and outbound `ApplicationMessage`s and serves
+/// CHATHISTORY-style scrollback queries, layered on top of `DbConnection`.
+pub struct HistoryStore;
+
+impl HistoryStore {
+    /// Creates the history tables if they don't already exist.
+    pub fn run_migrations(conn: &mut DbConnection) -> Result<(), Error> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS history_groups (
+                group_id BLOB PRIMARY KEY
+            );
+            CREATE TABLE IF NOT EXISTS history_messages (
+                group_id BLOB NOT NULL,
+                sequence INTEGER NOT NULL,
+                sender_identity BLOB NOT NULL,
+                timestamp_ms INTEGER NOT NULL,
+                epoch INTEGER NOT NULL,
+                is_outbound INTEGER NOT NULL,
+                body BLOB NOT NULL,
+                PRIMARY KEY (group_id, sequence)
+            );
+            CREATE TABLE IF NOT EXISTS history_delivery_state (
+                group_id BLOB NOT NULL,
+                sequence INTEGER NOT NULL,
+                delivered INTEGER NOT NULL DEFAULT 0,
+                merged INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (group_id, sequence)
+            );",
+        )
+    }
+
+    /// Registers `group_id` as known to history, e.g. right after joining a
+    /// group, so `history_*` queries report `Empty` instead of
+    /// `TargetNotFound` for a group that hasn't had a message yet.
+    /// `record_message` also does this on a group's first message, so
+    /// calling this beforehand is optional but makes an empty conversation
+    /// distinguishable from an unknown one immediately after joining.
+    pub fn register_group(conn: &mut DbConnection, group_id: &[u8]) -> Result<(), Error> {
+        conn.execute(
+            "INSERT OR IGNORE INTO history_groups (group_id) VALUES (?1)",
+            rusqlite::params![group_id],
+        )?;
+        Ok(())
+    }
+
+    /// Persists one message and its delivery/merge state atomically, assigning
+    /// it the next sequence number for the group.
+    pub fn record_message(
+        conn: &mut DbConnection,
+        group_id: &[u8],
+        sender_identity: &[u8],
+        epoch: u64,
+        is_outbound: bool,
+        body: &[u8],
+        timestamp_ms: i64,
+        merged: bool,
+    ) -> Result<StoredMessage, Error> {
+        let mut txn = conn.new_transaction()?;
+
+        txn.execute(
+            "INSERT OR IGNORE INTO history_groups (group_id) VALUES (?1)",
+            rusqlite::params![group_id],
+        )?;
+
+        let sequence = txn.query_row(
+            "SELECT COALESCE(MAX(sequence), -1) + 1 FROM history_messages WHERE group_id = ?1",
+            rusqlite::params![group_id],
+            |row| row.get(0),
+        )?;
+
+        txn.execute(
+            "INSERT INTO history_messages
+                (group_id, sequence, sender_identity, timestamp_ms, epoch, is_outbound, body)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            rusqlite::params![
+                group_id,
+                sequence,
+                sender_identity,
+                timestamp_ms,
+                epoch as i64,
+                is_outbound,
+                body
+            ],
+        )?;
+
+        txn.execute(
+            "INSERT INTO history_delivery_state (group_id, sequence, delivered, merged)
+             VALUES (?1, ?2, 1, ?3)",
+            rusqlite::params![group_id, sequence, merged],
+        )?;
+
+        txn.commit()?;
+
+        Ok(StoredMessage {
+            group_id: group_id.to_vec(),
+            sender_identity: sender_identity.to_vec(),
+            sequence,
+            timestamp_ms,
+            epoch,
+            is_outbound,
+            body: body.to_vec(),
+        })
+    }
+
+    /// The most recent `limit` messages in the group, newest last.
+    pub fn history_latest(
+        conn: &mut DbConnection,
+        group_id: &[u8],
+        limit: usize,
+    ) -> Result<HistoryResult, Error> {
+        if !Self::group_known(conn, group_id)? {
+            return Ok(HistoryResult::TargetNotFound);
+        }
+        let mut messages = Self::fetch(
+            conn,
+            "SELECT group_id, sequence, sender_identity, timestamp_ms, epoch, is_outbound, body
+             FROM history_messages WHERE group_id = ?1 ORDER BY sequence DESC LIMIT ?2",
+            rusqlite::params![group_id, limit as i64],
+        )?;
+        messages.reverse();
+        Ok(Self::wrap(messages))
+    }
+
+    /// Up to `limit` messages strictly before `anchor`, oldest first.
+    pub fn history_before(
+        conn: &mut DbConnection,
+        group_id: &[u8],
+        anchor: Anchor,
+        limit: usize,
+    ) -> Result<HistoryResult, Error> {
+        if !Self::group_known(conn, group_id)? {
+            return Ok(HistoryResult::TargetNotFound);
+        }
+        let (column, value) = Self::anchor_column(anchor);
+        let mut messages = Self::fetch(
+            conn,
+            &format!(
+                "SELECT group_id, sequence, sender_identity, timestamp_ms, epoch, is_outbound, body
+                 FROM history_messages WHERE group_id = ?1 AND {column} < ?2
+                 ORDER BY sequence DESC LIMIT ?3"
+            ),
+            rusqlite::params![group_id, value, limit as i64],
+        )?;
+        messages.reverse();
+        Ok(Self::wrap(messages))
+    }
+
+    /// Up to `limit` messages strictly after `anchor`, oldest first.
+    pub fn history_after(
+        conn: &mut DbConnection,
+        group_id: &[u8],
+        anchor: Anchor,
+        limit: usize,
+    ) -> Result<HistoryResult, Error> {
+        if !Self::group_known(conn, group_id)? {
+            return Ok(HistoryResult::TargetNotFound);
+        }
+        let (column, value) = Self::anchor_column(anchor);
+        let messages = Self::fetch(
+            conn,
+            &format!(
+                "SELECT group_id, sequence, sender_identity, timestamp_ms, epoch, is_outbound, body
+                 FROM history_messages WHERE group_id = ?1 AND {column} > ?2
+                 ORDER BY sequence ASC LIMIT ?3"
+            ),
+            rusqlite::params![group_id, value, limit as i64],
+        )?;
+        Ok(Self::wrap(messages))
+    }
+
+    /// All messages with an anchor value in `[from, to]`, oldest first.
+    pub fn history_between(
+        conn: &mut DbConnection,
+        group_id: &[u8],
+        from: Anchor,
+        to: Anchor,
+    ) -> Result<HistoryResult, Error> {
+        if !Self::group_known(conn, group_id)? {
+            return Ok(HistoryResult::TargetNotFound);
+        }
+        let (from_column, from_value) = Self::anchor_column(from);
+        let (to_column, to_value) = Self::anchor_column(to);
+        let messages = Self::fetch(
+            conn,
+            &format!(
+                "SELECT group_id, sequence, sender_identity, timestamp_ms, epoch, is_outbound, body
+                 FROM history_messages WHERE group_id = ?1 AND {from_column} >= ?2 AND {to_column} <= ?3
+                 ORDER BY sequence ASC"
+            ),
+            rusqlite::params![group_id, from_value, to_value],
+        )?;
+        Ok(Self::wrap(messages))
+    }
+
+    fn wrap(messages: Vec<StoredMessage>) -> HistoryResult {
+        if messages.is_empty() {
+            HistoryResult::Empty
+        } else {
+            HistoryResult::Messages(messages)
+        }
+    }
+
+    fn anchor_column(anchor: Anchor) -> (&'static str, i64) {
+        match anchor {
+            Anchor::Sequence(seq) => ("sequence", seq),
+            Anchor::TimestampMs(ts) => ("timestamp_ms", ts),
+        }
+    }
+
+    fn group_known(conn: &mut DbConnection, group_id: &[u8]) -> Result<bool, Error> {
+        conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM history_groups WHERE group_id = ?1)",
+            rusqlite::params![group_id],
+            |row| row.get(0),
+        )
+    }
+
+    fn fetch(
+        conn: &mut DbConnection,
+        sql: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Vec<StoredMessage>, Error> {
+        let mut stmt = conn.prepare(sql)?;
+        let rows = stmt
+            .query_map(params, |row| {
+                Ok(StoredMessage {
+                    group_id: row.get(0)?,
+                    sequence: row.get(1)?,
+                    sender_identity: row.get(2)?,
+                    timestamp_ms: row.get(3)?,
+                    epoch: row.get::<_, i64>(4)? as u64,
+                    is_outbound: row.get(5)?,
+                    body: row.get(6)?,
+                })
+            })
+            .map_err(|e| Error::DatabaseError(e.to_string()))?;
+
+        let mut messages = Vec::new();
+        for row in rows {
+            messages.push(row.map_err(|e| Error::DatabaseError(e.to_string()))?);
+        }
+        Ok(messages)
+    }
+}
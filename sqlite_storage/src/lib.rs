@@ -0,0 +1,7 @@
+pub mod db_connection;
+pub mod delivery;
+pub mod error;
+pub mod history;
+
+pub use db_connection::DbConnection;
+pub use error::Error;